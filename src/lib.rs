@@ -18,6 +18,7 @@
 
 use indexmap::IndexMap;
 use nu_protocol::{ShellError, Span, Spanned, Value};
+use sha1::{Digest, Sha1};
 
 mod nu;
 
@@ -25,65 +26,316 @@ mod nu;
 #[derive(Debug, Default)]
 pub struct FromBencode;
 
-fn convert_bencode_to_value(value: bt_bencode::Value, span: Span) -> Result<Value, ShellError> {
-    Ok(match value {
-        bt_bencode::Value::Int(num) => match num {
-            bt_bencode::value::Number::Signed(signed_num) => Value::Int {
-                val: signed_num,
-                span,
-            },
-            bt_bencode::value::Number::Unsigned(unsigned_num) => i64::try_from(unsigned_num)
-                .map(|val| Value::Int { val, span })
-                .map_err(|_| {
-                    ShellError::UnsupportedInput("expected a compatible number".into(), span)
-                })?,
-        },
-        bt_bencode::Value::ByteStr(byte_str) => match String::from_utf8(byte_str.into_vec()) {
-            Ok(s) => Value::String { val: s, span },
-            Err(err) => Value::Binary {
-                val: err.into_bytes(),
-                span,
-            },
-        },
-        bt_bencode::Value::List(list) => Value::List {
-            vals: list
-                .into_iter()
-                .map(|val| convert_bencode_to_value(val, span))
-                .collect::<Result<Vec<_>, ShellError>>()?,
-            span,
-        },
-        bt_bencode::Value::Dict(dict) => {
-            let mut collected = Spanned {
-                item: IndexMap::new(),
-                span,
-            };
-
-            for (key, value) in dict {
-                let key = String::from_utf8(key.into_vec()).map_err(|e| {
-                    ShellError::UnsupportedInput(
-                        format!("Unexpected bencode data {:?}:{:?}", e.into_bytes(), value),
-                        span,
-                    )
-                })?;
-                let value = convert_bencode_to_value(value, span)?;
-                collected.item.insert(key, value);
+fn invalid_bencode_err(span: Span) -> ShellError {
+    ShellError::CantConvert("bencode data".into(), "binary".into(), span, None)
+}
+
+/// Parses the byte string beginning at `pos` (a `<len>:` prefix followed by `len` raw bytes) and
+/// returns its content along with the position just past it.
+///
+/// `offset` is added to every offset used to build an error's [`Span`], so the error points at
+/// the actual failure site within the original input (the caller's `span.start`) rather than the
+/// whole input.
+fn parse_byte_string(bytes: &[u8], pos: usize, offset: usize) -> Result<(Vec<u8>, usize), ShellError> {
+    let colon = bytes[pos..]
+        .iter()
+        .position(|&b| b == b':')
+        .map(|i| pos + i)
+        .ok_or_else(|| invalid_bencode_err(Span::new(offset + pos, offset + bytes.len())))?;
+    let len: usize = std::str::from_utf8(&bytes[pos..colon])
+        .map_err(|_e| invalid_bencode_err(Span::new(offset + pos, offset + colon)))?
+        .parse()
+        .map_err(|_e| invalid_bencode_err(Span::new(offset + pos, offset + colon)))?;
+
+    let start = colon + 1;
+    let end = start
+        .checked_add(len)
+        .filter(|&end| end <= bytes.len())
+        .ok_or_else(|| invalid_bencode_err(Span::new(offset + start, offset + bytes.len())))?;
+
+    Ok((bytes[start..end].to_vec(), end))
+}
+
+/// Parses the bencode element beginning at `pos` and returns the equivalent [`Value`], with its
+/// span computed from the element's real byte offsets in `bytes`, along with the position just
+/// past it.
+///
+/// `offset` is added to every computed offset (including error spans) so spans line up with
+/// `bytes`' position within the original input (the caller's `span.start`).
+fn parse_value(bytes: &[u8], pos: usize, offset: usize) -> Result<(Value, usize), ShellError> {
+    let start = pos;
+    match bytes.get(pos) {
+        Some(b'i') => {
+            let end = bytes[pos..]
+                .iter()
+                .position(|&b| b == b'e')
+                .map(|i| pos + i)
+                .ok_or_else(|| invalid_bencode_err(Span::new(offset + pos, offset + bytes.len())))?;
+            let val: i64 = std::str::from_utf8(&bytes[pos + 1..end])
+                .map_err(|_e| invalid_bencode_err(Span::new(offset + pos, offset + end)))?
+                .parse()
+                .map_err(|_e| invalid_bencode_err(Span::new(offset + pos, offset + end)))?;
+            let next = end + 1;
+            Ok((
+                Value::Int {
+                    val,
+                    span: Span::new(offset + start, offset + next),
+                },
+                next,
+            ))
+        }
+        Some(b'l') => {
+            let mut cur = pos + 1;
+            let mut vals = Vec::new();
+            while bytes.get(cur) != Some(&b'e') {
+                let (val, next) = parse_value(bytes, cur, offset)?;
+                vals.push(val);
+                cur = next;
             }
+            let next = cur + 1;
+            Ok((
+                Value::List {
+                    vals,
+                    span: Span::new(offset + start, offset + next),
+                },
+                next,
+            ))
+        }
+        Some(b'd') => {
+            let mut cur = pos + 1;
+            let mut collected = IndexMap::new();
+            while bytes.get(cur) != Some(&b'e') {
+                let key_start = cur;
+                let (key_bytes, next) = parse_byte_string(bytes, cur, offset)?;
+                cur = next;
+                let (val, next) = parse_value(bytes, cur, offset)?;
+                cur = next;
 
-            Value::from(collected)
+                // Bencode dictionary keys are arbitrary byte strings; most are UTF-8 in
+                // practice, but DHT and peer-extension dicts can carry binary keys. Rather
+                // than aborting the whole decode, fall back to a deterministic hex-escaped
+                // column name so the value is still reachable.
+                let key = String::from_utf8(key_bytes).unwrap_or_else(|err| {
+                    err.as_bytes()
+                        .iter()
+                        .map(|byte| format!("%{byte:02x}"))
+                        .collect()
+                });
+                // A literal key could coincidentally collide with another key's escaped form
+                // (or the file could simply repeat a key); either way, silently clobbering one
+                // value with the other would lose data, so reject it instead.
+                if collected.insert(key.clone(), val).is_some() {
+                    return Err(ShellError::UnsupportedInput(
+                        format!("duplicate bencode dictionary key: {key:?}"),
+                        Span::new(offset + key_start, offset + cur),
+                    ));
+                }
+            }
+            let next = cur + 1;
+            Ok((
+                Value::from(Spanned {
+                    item: collected,
+                    span: Span::new(offset + start, offset + next),
+                }),
+                next,
+            ))
+        }
+        Some(b'0'..=b'9') => {
+            let (byte_str, next) = parse_byte_string(bytes, pos, offset)?;
+            let value_span = Span::new(offset + start, offset + next);
+            Ok((
+                match String::from_utf8(byte_str) {
+                    Ok(s) => Value::String {
+                        val: s,
+                        span: value_span,
+                    },
+                    Err(err) => Value::Binary {
+                        val: err.into_bytes(),
+                        span: value_span,
+                    },
+                },
+                next,
+            ))
         }
-    })
+        _ => Err(invalid_bencode_err(Span::new(
+            offset + pos,
+            offset + bytes.len(),
+        ))),
+    }
 }
 
 /// Converts a byte slice into a [`Value`].
 ///
+/// Each decoded node is given the [`Span`] of the bencode element it actually came from (rather
+/// than the whole input's span), so downstream error reporting and span-aware operations can
+/// point at the exact offending element — including on a parse failure, where the error span
+/// marks the actual failure site rather than the whole input.
+///
 /// # Errors
 ///
 /// Returns an error if the input is not valid bencode data.
 pub fn from_bytes_to_value(bytes: &[u8], span: Span) -> Result<Value, ShellError> {
-    let value = bt_bencode::from_slice(bytes).map_err(|_e| {
-        ShellError::CantConvert("bencode data".into(), "binary".into(), span, None)
-    })?;
-    convert_bencode_to_value(value, span)
+    let (value, end) = parse_value(bytes, 0, span.start)?;
+    if end != bytes.len() {
+        return Err(invalid_bencode_err(Span::new(
+            span.start + end,
+            span.start + bytes.len(),
+        )));
+    }
+    Ok(value)
+}
+
+/// Converts Nu structured values to bencode data.
+#[derive(Debug, Default)]
+pub struct ToBencode;
+
+/// Reverses the `%xx` hex-escape the decoder applies to a non-UTF-8 dictionary key (each raw
+/// byte written out as `%` followed by two hex digits), so `from bencode | to bencode` reproduces
+/// the original bytes instead of encoding the escaped placeholder text literally. Returns `None`
+/// if `col` isn't exactly what the decoder produces for a key (lowercase `%xx` triples which
+/// unescape to bytes that are not valid UTF-8), in which case it should be encoded as-is. A
+/// literal UTF-8 key which already has that exact form (such as `%e2%28`) cannot be told apart
+/// from an escaped one and is encoded as the unescaped raw bytes.
+fn unescape_bencode_key(col: &str) -> Option<Vec<u8>> {
+    let bytes = col.as_bytes();
+    if bytes.is_empty() || bytes.len() % 3 != 0 {
+        return None;
+    }
+
+    let is_lower_hex = |b: u8| matches!(b, b'0'..=b'9' | b'a'..=b'f');
+    let unescaped = bytes
+        .chunks(3)
+        .map(|chunk| match chunk {
+            [b'%', hi, lo] if is_lower_hex(*hi) && is_lower_hex(*lo) => {
+                u8::from_str_radix(std::str::from_utf8(&[*hi, *lo]).ok()?, 16).ok()
+            }
+            _ => None,
+        })
+        .collect::<Option<Vec<u8>>>()?;
+
+    // The decoder only escapes keys which are not valid UTF-8.
+    if std::str::from_utf8(&unescaped).is_ok() {
+        return None;
+    }
+    Some(unescaped)
+}
+
+/// Converts a [`Value`] into bencode-encoded bytes.
+///
+/// # Errors
+///
+/// Returns an error if the value contains data which cannot be represented in bencode, such as
+/// floats, booleans, dates, or null values.
+pub fn from_value_to_bytes(value: &Value, span: Span) -> Result<Vec<u8>, ShellError> {
+    match value {
+        Value::Int { val, .. } => Ok(format!("i{val}e").into_bytes()),
+        Value::String { val, .. } => {
+            let mut out = format!("{}:", val.len()).into_bytes();
+            out.extend_from_slice(val.as_bytes());
+            Ok(out)
+        }
+        Value::Binary { val, .. } => {
+            let mut out = format!("{}:", val.len()).into_bytes();
+            out.extend_from_slice(val);
+            Ok(out)
+        }
+        Value::List { vals, .. } => {
+            let mut out = vec![b'l'];
+            for val in vals {
+                out.extend(from_value_to_bytes(val, span)?);
+            }
+            out.push(b'e');
+            Ok(out)
+        }
+        Value::Record { cols, vals, .. } => {
+            let mut entries = cols
+                .iter()
+                .map(|col| unescape_bencode_key(col).unwrap_or_else(|| col.clone().into_bytes()))
+                .zip(vals)
+                .map(|(key, val)| Ok((key, from_value_to_bytes(val, span)?)))
+                .collect::<Result<Vec<(Vec<u8>, Vec<u8>)>, ShellError>>()?;
+
+            // Bencode dictionaries must be canonical: keys sorted by raw byte sequence, with
+            // no duplicates.
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            if let Some(pair) = entries.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+                return Err(ShellError::UnsupportedInput(
+                    format!(
+                        "duplicate bencode dictionary key: {:?}",
+                        String::from_utf8_lossy(&pair[0].0)
+                    ),
+                    span,
+                ));
+            }
+
+            let mut out = vec![b'd'];
+            for (key, val) in entries {
+                out.extend(format!("{}:", key.len()).into_bytes());
+                out.extend(key);
+                out.extend(val);
+            }
+            out.push(b'e');
+            Ok(out)
+        }
+        _ => Err(ShellError::UnsupportedInput(
+            "bencode can only represent ints, strings, binary data, lists, and records".into(),
+            span,
+        )),
+    }
+}
+
+/// Computes the info hash of a `.torrent` file's `info` dictionary.
+#[derive(Debug, Default)]
+pub struct TorrentInfoHash;
+
+/// Computes the SHA-1 info hash of the `info` dictionary within bencode-encoded torrent
+/// metadata.
+///
+/// The bytes hashed are the original `info` value's byte range as it appears in `bytes`, not a
+/// re-encoding of it, so the result matches the hash produced by conforming BitTorrent clients
+/// even for a non-canonically-ordered `info` dict.
+///
+/// # Errors
+///
+/// Returns an error if `bytes` is not valid bencode, the top-level value is not a dictionary, or
+/// no `info` key is present.
+pub fn compute_info_hash(bytes: &[u8], span: Span) -> Result<String, ShellError> {
+    let (value, end) = parse_value(bytes, 0, 0)?;
+    if end != bytes.len() {
+        return Err(invalid_bencode_err(Span::new(end, bytes.len())));
+    }
+
+    let Value::Record { cols, vals, .. } = value else {
+        return Err(ShellError::UnsupportedInput(
+            "expected a bencode dictionary at the top level of a torrent file".into(),
+            span,
+        ));
+    };
+
+    let info_span = cols
+        .iter()
+        .zip(vals.iter())
+        .find(|(col, _)| col.as_str() == "info")
+        .map(|(_, val)| match val {
+            Value::Int { span, .. }
+            | Value::String { span, .. }
+            | Value::Binary { span, .. }
+            | Value::List { span, .. }
+            | Value::Record { span, .. } => *span,
+            _ => span,
+        })
+        .ok_or_else(|| {
+            ShellError::UnsupportedInput(
+                "torrent file is missing an `info` dictionary".into(),
+                span,
+            )
+        })?;
+
+    let mut hasher = Sha1::new();
+    hasher.update(&bytes[info_span.start..info_span.end]);
+    let digest = hasher.finalize();
+
+    Ok(digest.iter().map(|byte| format!("{byte:02x}")).collect())
 }
 
 #[cfg(test)]
@@ -105,4 +357,131 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn decode_preserves_non_utf8_dict_keys() -> Result<(), bt_bencode::Error> {
+        let mut dict = bt_bencode::value::Dict::new();
+        dict.insert(vec![0xe2, 0x28].into(), bt_bencode::Value::from(1));
+        let bencode_bytes = bt_bencode::to_vec(&bt_bencode::Value::Dict(dict))?;
+
+        let span = Span::new(0, bencode_bytes.len());
+        let nu_value = from_bytes_to_value(&bencode_bytes, span).unwrap();
+        let Value::Record { cols, .. } = nu_value else {
+            panic!("expected a record");
+        };
+        assert_eq!(cols, vec!["%e2%28".to_string()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn decode_rejects_key_collision_with_escaped_form() -> Result<(), bt_bencode::Error> {
+        let mut dict = bt_bencode::value::Dict::new();
+        dict.insert(b"%e2%28".to_vec().into(), bt_bencode::Value::from(1));
+        dict.insert(vec![0xe2, 0x28].into(), bt_bencode::Value::from(2));
+        let bencode_bytes = bt_bencode::to_vec(&bt_bencode::Value::Dict(dict))?;
+
+        let span = Span::new(0, bencode_bytes.len());
+        assert!(from_bytes_to_value(&bencode_bytes, span).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn decode_computes_per_value_spans() -> Result<(), bt_bencode::Error> {
+        let mut dict = bt_bencode::value::Dict::new();
+        dict.insert(b"a".to_vec().into(), bt_bencode::Value::from(1));
+        let bencode_bytes = bt_bencode::to_vec(&bt_bencode::Value::Dict(dict))?;
+        // d1:ai1ee
+        assert_eq!(bencode_bytes, b"d1:ai1ee");
+
+        let span = Span::new(0, bencode_bytes.len());
+        let nu_value = from_bytes_to_value(&bencode_bytes, span).unwrap();
+        let Value::Record { vals, span: dict_span, .. } = nu_value else {
+            panic!("expected a record");
+        };
+        assert_eq!(dict_span, span);
+
+        let Value::Int { span: int_span, .. } = vals[0] else {
+            panic!("expected an int");
+        };
+        assert_eq!(int_span, Span::new(4, 7));
+
+        Ok(())
+    }
+
+    #[test]
+    fn decode_rejects_trailing_garbage() {
+        let span = Span::test_data();
+        let err = from_bytes_to_value(b"i1eJUNK", span).unwrap_err();
+        let ShellError::CantConvert(_, _, err_span, _) = err else {
+            panic!("expected a CantConvert error");
+        };
+        // The error should point at the garbage (bytes 3..7), not the whole input.
+        assert_eq!(err_span, Span::new(span.start + 3, span.start + 7));
+    }
+
+    #[test]
+    fn simple_encode() {
+        let span = Span::test_data();
+        let value = Value::String {
+            val: "hello world".to_string(),
+            span,
+        };
+
+        let bencode_bytes = from_value_to_bytes(&value, span).unwrap();
+        assert_eq!(bencode_bytes, b"11:hello world");
+    }
+
+    #[test]
+    fn encode_record_sorts_keys_canonically() {
+        let span = Span::test_data();
+        let value = Value::Record {
+            cols: vec!["zebra".to_string(), "ant".to_string()],
+            vals: vec![
+                Value::Int { val: 1, span },
+                Value::Int { val: 2, span },
+            ],
+            span,
+        };
+
+        let bencode_bytes = from_value_to_bytes(&value, span).unwrap();
+        assert_eq!(bencode_bytes, b"d3:anti2e5:zebrai1ee");
+    }
+
+    #[test]
+    fn encode_record_rejects_duplicate_keys() {
+        let span = Span::test_data();
+        let value = Value::Record {
+            cols: vec!["a".to_string(), "a".to_string()],
+            vals: vec![Value::Int { val: 1, span }, Value::Int { val: 2, span }],
+            span,
+        };
+
+        assert!(from_value_to_bytes(&value, span).is_err());
+    }
+
+    #[test]
+    fn round_trips_binary_dict_key() -> Result<(), bt_bencode::Error> {
+        let mut dict = bt_bencode::value::Dict::new();
+        dict.insert(vec![0xe2, 0x28].into(), bt_bencode::Value::from(1));
+        let bencode_bytes = bt_bencode::to_vec(&bt_bencode::Value::Dict(dict))?;
+
+        let span = Span::new(0, bencode_bytes.len());
+        let nu_value = from_bytes_to_value(&bencode_bytes, span).unwrap();
+        let round_tripped = from_value_to_bytes(&nu_value, span).unwrap();
+        assert_eq!(round_tripped, bencode_bytes);
+
+        Ok(())
+    }
+
+    #[test]
+    fn info_hash_of_minimal_torrent() {
+        let bencode_bytes = b"d4:infod6:lengthi0eee".to_vec();
+        let span = Span::test_data();
+
+        let info_hash = compute_info_hash(&bencode_bytes, span).unwrap();
+        // `sha1sum` of the literal `info` value bytes `d6:lengthi0ee`.
+        assert_eq!(info_hash, "26f0b584fa6fea9ccc2c627f8f6df9feb752ed96");
+    }
 }